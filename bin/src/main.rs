@@ -1,5 +1,5 @@
 use axum::Router;
-use oauth_decap_github_lib::oauth_router;
+use oauth_decap_github_lib::oauth_router_from_env;
 use std::env;
 use std::process::exit;
 use tokio::net::TcpListener;
@@ -21,7 +21,12 @@ async fn main() {
         exit(1);
     }
 
-    let app = Router::new().merge(oauth_router());
+    if let Err(_) = env::var("REDIRECT_URI") {
+        eprintln!("error: undefined environment variable `REDIRECT_URI`.");
+        exit(1);
+    }
+
+    let app = Router::new().merge(oauth_router_from_env());
 
     let listener = TcpListener::bind("0.0.0.0:3005").await.unwrap();
 
@@ -1,87 +1,315 @@
-//! Decap CMS OAuth provider for GitHub.
-//! The following environment variables must be set for it to work properly:
-//! `CLIENT_ID`  and `SECRET`. For instructions on how to set up an OAuth app and get these values, refer to
+//! Decap CMS OAuth provider for GitHub, GitLab, and any other provider speaking the same
+//! Authorization Code flow.
+//!
+//! Embedders register one [`ProviderConfig`] per provider they want to serve and pass the map to
+//! [`oauth_router`]. For the common single-provider case, set `CLIENT_ID`, `SECRET` and
+//! `REDIRECT_URI` and call [`oauth_router_from_env`] instead; it builds a `github` provider from
+//! them. For instructions on how to set up an OAuth app and get these values, refer to
 //! [GitHub's documentation](https://docs.github.com/en/apps/oauth-apps/building-oauth-apps/creating-an-oauth-app).
+//!
+//! To serve GitLab (or a self-hosted instance of either) instead, set `PROVIDER` to `gitlab` and
+//! `HOSTNAME` to the instance's URL. Set `OAUTH_USE_PKCE=true` to additionally perform the
+//! Authorization Code flow with PKCE; GitLab always requires it, so it defaults on for `gitlab`
+//! and off otherwise.
+//!
+//! [`StaticProvider`] only covers the built-in templates above; a fully custom provider (one
+//! speaking the Authorization Code flow at arbitrary endpoints) is supported by building a
+//! [`Provider`] directly and wrapping it in a [`ProviderConfig`] instead of going through
+//! [`StaticProvider`].
 use axum::{
-    extract::Query,
-    http::{HeaderMap, StatusCode},
+    extract::{Query, State},
+    http::StatusCode,
     response::{Html, IntoResponse, Redirect, Response},
     routing, Router,
 };
 use oauth2::{
-    basic::BasicClient, reqwest::http_client, AccessToken, AuthUrl, AuthorizationCode, ClientId,
-    ClientSecret, CsrfToken, RedirectUrl, Scope, TokenResponse, TokenUrl,
+    basic::{BasicClient, BasicTokenResponse},
+    AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, PkceCodeChallenge,
+    PkceCodeVerifier, RedirectUrl, Scope, TokenResponse, TokenUrl,
 };
 use std::collections::HashMap;
 use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+use tower_cookies::{cookie::SameSite, Cookie, Cookies};
 
 const TOKEN_HOST: &str = "https://github.com";
 const TOKEN_PATH: &str = "/login/oauth/access_token";
 const AUTH_PATH: &str = "/login/oauth/authorize";
+const CSRF_COOKIE_NAME: &str = "oauth_csrf";
+const PKCE_COOKIE_NAME: &str = "oauth_pkce_verifier";
+const HTTP_TIMEOUT_SECS: u64 = 10;
 
-fn create_client() -> BasicClient {
-    let client_id = env::var("CLIENT_ID").expect("CLIENT_ID env variable should be defined");
-    let secret = env::var("SECRET").expect("SECRET env variable should be defined");
+/// A registered OAuth provider's endpoints and default scopes.
+#[derive(Clone, Debug)]
+pub struct Provider {
+    pub name: String,
+    pub auth_url: String,
+    pub token_url: String,
+    pub default_scopes: Vec<String>,
+}
+
+/// Endpoint templates for providers this crate ships support for out of the box. `hostname` lets
+/// the same template serve self-hosted instances (GitHub Enterprise, a self-managed GitLab). For
+/// anything else, build a [`Provider`] directly instead of going through `StaticProvider`.
+pub enum StaticProvider {
+    GitHub,
+    GitLab,
+}
+
+impl StaticProvider {
+    pub fn provider(&self, hostname: &str) -> Provider {
+        match self {
+            StaticProvider::GitHub => Provider {
+                name: "github".to_string(),
+                auth_url: format!("{}{}", hostname, AUTH_PATH),
+                token_url: format!("{}{}", hostname, TOKEN_PATH),
+                default_scopes: vec!["repo".to_string()],
+            },
+            StaticProvider::GitLab => Provider {
+                name: "gitlab".to_string(),
+                auth_url: format!("{}/oauth/authorize", hostname),
+                token_url: format!("{}/oauth/token", hostname),
+                default_scopes: vec!["api".to_string()],
+            },
+        }
+    }
+}
+
+/// A provider's OAuth app credentials and the redirect URI registered with it.
+#[derive(Clone)]
+pub struct ProviderConfig {
+    pub provider: Provider,
+    pub client_id: String,
+    pub client_secret: String,
+    pub redirect_uri: String,
+    pub use_pkce: bool,
+}
+
+/// Shared state for the OAuth routes, built once at router-creation time.
+#[derive(Clone)]
+struct OauthState {
+    http_client: reqwest::Client,
+    providers: Arc<HashMap<String, ProviderConfig>>,
+}
+
+fn build_http_client() -> reqwest::Client {
+    let timeout_secs = env::var("OAUTH_HTTP_TIMEOUT_SECS")
+        .ok()
+        .and_then(|var| var.parse().ok())
+        .unwrap_or(HTTP_TIMEOUT_SECS);
+
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(timeout_secs))
+        .build()
+        .expect("Failed to build the shared reqwest client")
+}
+
+/// Performs the token request against `http_client`, mirroring `oauth2::reqwest::async_http_client`
+/// but reusing a single connection-pooled client instead of creating one per call.
+async fn async_http_client(
+    http_client: reqwest::Client,
+    request: oauth2::HttpRequest,
+) -> Result<oauth2::HttpResponse, oauth2::reqwest::Error<reqwest::Error>> {
+    let mut request_builder = http_client
+        .request(request.method, request.url.as_str())
+        .body(request.body);
+
+    for (name, value) in &request.headers {
+        request_builder = request_builder.header(name.as_str(), value.as_bytes());
+    }
 
+    let request = request_builder
+        .build()
+        .map_err(oauth2::reqwest::Error::Reqwest)?;
+
+    let response = http_client
+        .execute(request)
+        .await
+        .map_err(oauth2::reqwest::Error::Reqwest)?;
+
+    let status_code = response.status();
+    let headers = response.headers().to_owned();
+    let body = response
+        .bytes()
+        .await
+        .map_err(oauth2::reqwest::Error::Reqwest)?
+        .to_vec();
+
+    Ok(oauth2::HttpResponse {
+        status_code,
+        headers,
+        body,
+    })
+}
+
+fn create_client(config: &ProviderConfig) -> BasicClient {
     BasicClient::new(
-        ClientId::new(client_id),
-        Some(ClientSecret::new(secret)),
-        AuthUrl::new(format!("{}{}", TOKEN_HOST, AUTH_PATH))
-            .expect("Auth URL should be a valid URL"),
+        ClientId::new(config.client_id.clone()),
+        Some(ClientSecret::new(config.client_secret.clone())),
+        AuthUrl::new(config.provider.auth_url.clone()).expect("Auth URL should be a valid URL"),
         Some(
-            TokenUrl::new(format!("{}{}", TOKEN_HOST, TOKEN_PATH))
+            TokenUrl::new(config.provider.token_url.clone())
                 .expect("Token URL should be a valid URL"),
         ),
     )
+    .set_redirect_uri(RedirectUrl::new(config.redirect_uri.clone()).expect("Invalid redirect URL"))
+}
+
+/// Builds the single provider configuration described by the legacy `CLIENT_ID`, `SECRET` and
+/// `REDIRECT_URI` environment variables, defaulting to `github` unless `PROVIDER` says otherwise.
+fn providers_from_env() -> HashMap<String, ProviderConfig> {
+    let provider_name = env::var("PROVIDER").unwrap_or_else(|_| "github".to_string());
+    let hostname = env::var("HOSTNAME").unwrap_or_else(|_| TOKEN_HOST.to_string());
+
+    let provider = match provider_name.as_str() {
+        "gitlab" => StaticProvider::GitLab.provider(&hostname),
+        _ => StaticProvider::GitHub.provider(&hostname),
+    };
+
+    let use_pkce = env::var("OAUTH_USE_PKCE")
+        .map(|var| var == "true")
+        .unwrap_or_else(|_| pkce_default_for_provider(&provider.name));
+
+    let config = ProviderConfig {
+        provider,
+        client_id: env::var("CLIENT_ID").expect("CLIENT_ID env variable should be defined"),
+        client_secret: env::var("SECRET").expect("SECRET env variable should be defined"),
+        redirect_uri: env::var("REDIRECT_URI")
+            .expect("REDIRECT_URI env variable should be defined"),
+        use_pkce,
+    };
+
+    let mut providers = HashMap::new();
+    providers.insert(config.provider.name.clone(), config);
+    providers
+}
+
+/// How long a CSRF/PKCE flow cookie survives: just enough for the redirect round-trip to the
+/// provider and back, not the rest of the browser session.
+const FLOW_COOKIE_MAX_AGE: tower_cookies::cookie::time::Duration =
+    tower_cookies::cookie::time::Duration::minutes(2);
+
+/// Builds a short-lived, `HttpOnly`, `Secure`, `SameSite=Lax` cookie used to carry OAuth flow
+/// state across the redirect to the provider.
+fn flow_cookie(name: &'static str, value: String) -> Cookie<'static> {
+    let mut cookie = Cookie::new(name, value);
+    cookie.set_path("/");
+    cookie.set_http_only(true);
+    cookie.set_secure(true);
+    cookie.set_same_site(SameSite::Lax);
+    cookie.set_max_age(FLOW_COOKIE_MAX_AGE);
+    cookie
+}
+
+/// GitLab (and similarly strict providers) require PKCE; classic GitHub does not, but accepts it.
+fn pkce_default_for_provider(provider: &str) -> bool {
+    provider == "gitlab"
+}
+
+/// Constant-time comparison, so a mismatched CSRF state can't be brute-forced via timing.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
 }
 
 /// The auth route.
-pub async fn auth(Query(params): Query<HashMap<String, String>>, headers: HeaderMap) -> Response {
-    let provider = match params.get("provider") {
+pub async fn auth(
+    Query(params): Query<HashMap<String, String>>,
+    cookies: Cookies,
+    State(state): State<OauthState>,
+) -> Response {
+    let provider_name = match params.get("provider") {
         Some(provider) => provider,
         None => {
             return (StatusCode::BAD_REQUEST, "No provider specified".to_string()).into_response()
         }
     };
 
-    if provider != "github" {
-        return (
-            StatusCode::BAD_REQUEST,
-            format!("Invalid provider {:?}", provider),
-        )
-            .into_response();
-    }
+    let config = match state.providers.get(provider_name) {
+        Some(config) => config,
+        None => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Unregistered provider `{}`", provider_name),
+            )
+                .into_response()
+        }
+    };
 
     let scope = match params.get("scope") {
         Some(scope) => scope.to_owned(),
-        None => "repo".to_string(),
-    };
-
-    let host = match headers.get("host") {
-        Some(host) => host.to_str().unwrap(),
-        None => return (StatusCode::BAD_REQUEST, "No host header".to_string()).into_response(),
+        None => config.provider.default_scopes.join(" "),
     };
 
-    let redirect_url = format!("https://{}/callback?provider={}", host, provider);
+    let client = create_client(config);
 
-    let client = create_client()
-        .set_redirect_uri(RedirectUrl::new(redirect_url).expect("Invalid redirect URL"));
+    let use_pkce = config.use_pkce;
 
-    let (auth_url, _csrf_state) = client
+    let mut auth_request = client
         .authorize_url(CsrfToken::new_random)
-        .add_scope(Scope::new(scope))
-        .url();
+        .add_scope(Scope::new(scope));
+
+    let pkce_verifier = if use_pkce {
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+        auth_request = auth_request.set_pkce_challenge(pkce_challenge);
+        Some(pkce_verifier)
+    } else {
+        None
+    };
+
+    let (auth_url, csrf_state) = auth_request.url();
+
+    cookies.add(flow_cookie(CSRF_COOKIE_NAME, csrf_state.secret().to_owned()));
+    if let Some(pkce_verifier) = pkce_verifier {
+        cookies.add(flow_cookie(
+            PKCE_COOKIE_NAME,
+            pkce_verifier.secret().to_owned(),
+        ));
+    }
 
     Redirect::to(&auth_url.to_string()).into_response()
 }
 
-fn login_response(provider: &str, status: &str, token: &AccessToken) -> Html<String> {
+/// Escapes a token's secret so it can't break out of the JSON object embedded inside the
+/// single-quoted JS string literal built by `login_response`.
+fn escape_for_postmessage(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\'', "\\'")
+}
+
+/// Extra, provider-dependent fields appended to the JSON payload posted back to Decap, so it can
+/// refresh the session without forcing re-auth (e.g. GitLab returns a refresh token and expiry;
+/// GitHub's classic OAuth apps return neither).
+fn extra_token_fields(token: &BasicTokenResponse) -> String {
+    let mut fields = String::new();
+
+    if let Some(refresh_token) = token.refresh_token() {
+        fields.push_str(&format!(
+            r#","refresh_token":"{}""#,
+            escape_for_postmessage(refresh_token.secret())
+        ));
+    }
+
+    if let Some(expires_in) = token.expires_in() {
+        fields.push_str(&format!(r#","expires_in":{}"#, expires_in.as_secs()));
+    }
+
+    fields
+}
+
+fn login_response(provider: &str, status: &str, token: &BasicTokenResponse) -> Html<String> {
+    let extra_fields = extra_token_fields(token);
+    let access_token = escape_for_postmessage(token.access_token().secret());
+
     Html(format!(
         r#"
     <script>
       const receiveMessage = (message) => {{
         window.opener.postMessage(
-          'authorization:{}:{}:{{"token":"{}","provider":"{}"}}',
+          'authorization:{}:{}:{{"token":"{}","provider":"{}"{}}}',
           message.origin
         );
 
@@ -94,27 +322,50 @@ fn login_response(provider: &str, status: &str, token: &AccessToken) -> Html<Str
     "#,
         provider,
         status,
-        token.secret(),
+        access_token,
         provider,
+        extra_fields,
         provider,
     ))
 }
 
 /// The callback route.
-pub async fn callback(Query(params): Query<HashMap<String, String>>) -> Response {
-    let provider = match params.get("provider") {
+pub async fn callback(
+    Query(params): Query<HashMap<String, String>>,
+    cookies: Cookies,
+    State(state): State<OauthState>,
+) -> Response {
+    let provider_name = match params.get("provider") {
         Some(provider) => provider,
         None => {
             return (StatusCode::BAD_REQUEST, "No provider specified".to_string()).into_response()
         }
     };
 
-    if provider != "github" {
-        return (
-            StatusCode::BAD_REQUEST,
-            format!("Invalid provider {:?}", provider),
-        )
-            .into_response();
+    let config = match state.providers.get(provider_name) {
+        Some(config) => config,
+        None => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Unregistered provider `{}`", provider_name),
+            )
+                .into_response()
+        }
+    };
+
+    let csrf_state = match params.get("state") {
+        Some(csrf_state) => csrf_state,
+        None => return (StatusCode::BAD_REQUEST, "Missing state parameter".to_string()).into_response(),
+    };
+
+    let csrf_cookie = match cookies.get(CSRF_COOKIE_NAME) {
+        Some(cookie) => cookie,
+        None => return (StatusCode::BAD_REQUEST, "Missing CSRF cookie".to_string()).into_response(),
+    };
+    cookies.remove(Cookie::from(CSRF_COOKIE_NAME));
+
+    if !constant_time_eq(csrf_state, csrf_cookie.value()) {
+        return (StatusCode::BAD_REQUEST, "CSRF state mismatch".to_string()).into_response();
     }
 
     let code = match params.get("code") {
@@ -122,21 +373,289 @@ pub async fn callback(Query(params): Query<HashMap<String, String>>) -> Response
         None => return (StatusCode::BAD_REQUEST, "Code is required".to_string()).into_response(),
     };
 
-    let client = create_client();
+    let client = create_client(config);
 
-    match client.exchange_code(code).request(http_client) {
+    let mut token_request = client.exchange_code(code);
+    if config.use_pkce {
+        if let Some(pkce_verifier) = cookies.get(PKCE_COOKIE_NAME) {
+            token_request = token_request
+                .set_pkce_verifier(PkceCodeVerifier::new(pkce_verifier.value().to_string()));
+        }
+    }
+    // Always clear it, even when unused: a stale verifier cookie from an abandoned PKCE flow for
+    // another provider must not leak into this (possibly non-PKCE) exchange.
+    cookies.remove(Cookie::from(PKCE_COOKIE_NAME));
+
+    let http_client = state.http_client.clone();
+    match token_request
+        .request_async(|request| async_http_client(http_client.clone(), request))
+        .await
+    {
         Ok(token) => (
             StatusCode::OK,
-            login_response(provider, "success", token.access_token()),
+            login_response(provider_name, "success", &token),
         )
             .into_response(),
         Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
 }
 
-/// Return a full Axum router with both routes used by OAuth.
-pub fn oauth_router() -> Router {
+/// Return a full Axum router with both OAuth routes, serving every provider in `providers`
+/// (keyed by [`Provider::name`]).
+pub fn oauth_router(providers: HashMap<String, ProviderConfig>) -> Router {
+    let state = OauthState {
+        http_client: build_http_client(),
+        providers: Arc::new(providers),
+    };
+
     Router::new()
         .route("/auth", routing::get(auth))
         .route("/callback", routing::get(callback))
+        .layer(tower_cookies::CookieManagerLayer::new())
+        .with_state(state)
+}
+
+/// Thin adapter over the legacy `CLIENT_ID`/`SECRET`/`REDIRECT_URI` environment variables, for
+/// the single `github` provider this crate originally served.
+pub fn oauth_router_from_env() -> Router {
+    oauth_router(providers_from_env())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{to_bytes, Body};
+    use axum::http::{header, Request};
+    use tower::ServiceExt;
+
+    fn test_providers(token_url: String, use_pkce: bool) -> HashMap<String, ProviderConfig> {
+        let provider = Provider {
+            name: "test".to_string(),
+            auth_url: "http://localhost/authorize".to_string(),
+            token_url,
+            default_scopes: vec!["repo".to_string()],
+        };
+
+        let config = ProviderConfig {
+            provider,
+            client_id: "client-id".to_string(),
+            client_secret: "client-secret".to_string(),
+            redirect_uri: "http://localhost/callback?provider=test".to_string(),
+            use_pkce,
+        };
+
+        let mut providers = HashMap::new();
+        providers.insert("test".to_string(), config);
+        providers
+    }
+
+    fn auth_request() -> Request<Body> {
+        Request::builder()
+            .uri("/auth?provider=test")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    fn extract_cookie(response: &Response, name: &str) -> Option<String> {
+        response
+            .headers()
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .find_map(|raw| {
+                Cookie::parse(raw.to_string())
+                    .ok()
+                    .filter(|cookie| cookie.name() == name)
+                    .map(|cookie| cookie.value().to_string())
+            })
+    }
+
+    async fn mock_token() -> axum::Json<serde_json::Value> {
+        axum::Json(serde_json::json!({
+            "access_token": "mock-access-token",
+            "token_type": "bearer",
+            "expires_in": 3600,
+            "refresh_token": "mock-refresh-token",
+        }))
+    }
+
+    /// Spawns a local server that stands in for a provider's token endpoint, returning its URL.
+    async fn spawn_mock_token_server() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/token", routing::post(mock_token));
+
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        format!("http://{}/token", addr)
+    }
+
+    #[tokio::test]
+    async fn callback_rejects_missing_state() {
+        let app = oauth_router(test_providers("http://localhost/token".to_string(), false));
+
+        let auth_response = app.clone().oneshot(auth_request()).await.unwrap();
+        let csrf_cookie = extract_cookie(&auth_response, CSRF_COOKIE_NAME).unwrap();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/callback?provider=test&code=abc")
+                    .header(header::COOKIE, format!("{}={}", CSRF_COOKIE_NAME, csrf_cookie))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("Missing state parameter"));
+    }
+
+    #[tokio::test]
+    async fn callback_rejects_mismatched_state() {
+        let app = oauth_router(test_providers("http://localhost/token".to_string(), false));
+
+        let auth_response = app.clone().oneshot(auth_request()).await.unwrap();
+        let csrf_cookie = extract_cookie(&auth_response, CSRF_COOKIE_NAME).unwrap();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/callback?provider=test&code=abc&state=not-the-right-state")
+                    .header(header::COOKIE, format!("{}={}", CSRF_COOKIE_NAME, csrf_cookie))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("CSRF state mismatch"));
+    }
+
+    #[tokio::test]
+    async fn callback_accepts_matching_state() {
+        let token_url = spawn_mock_token_server().await;
+        let app = oauth_router(test_providers(token_url, false));
+
+        let auth_response = app.clone().oneshot(auth_request()).await.unwrap();
+        let csrf_cookie = extract_cookie(&auth_response, CSRF_COOKIE_NAME).unwrap();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(format!(
+                        "/callback?provider=test&code=abc&state={}",
+                        csrf_cookie
+                    ))
+                    .header(header::COOKIE, format!("{}={}", CSRF_COOKIE_NAME, csrf_cookie))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("authorization:test:success"));
+    }
+
+    #[tokio::test]
+    async fn auth_includes_pkce_params_when_enabled() {
+        let app = oauth_router(test_providers("http://localhost/token".to_string(), true));
+
+        let response = app.oneshot(auth_request()).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let location = response
+            .headers()
+            .get(header::LOCATION)
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert!(location.contains("code_challenge="));
+        assert!(location.contains("code_challenge_method=S256"));
+    }
+
+    /// Exercises the whole async `request_async` path against a mock token endpoint, and checks
+    /// the resulting HTML matches what `login_response` builds directly from the same token.
+    #[tokio::test]
+    async fn callback_returns_success_html_via_async_token_exchange() {
+        let token_url = spawn_mock_token_server().await;
+        let app = oauth_router(test_providers(token_url, false));
+
+        let auth_response = app.clone().oneshot(auth_request()).await.unwrap();
+        let csrf_cookie = extract_cookie(&auth_response, CSRF_COOKIE_NAME).unwrap();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(format!(
+                        "/callback?provider=test&code=abc&state={}",
+                        csrf_cookie
+                    ))
+                    .header(header::COOKIE, format!("{}={}", CSRF_COOKIE_NAME, csrf_cookie))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        let mut expected_token = BasicTokenResponse::new(
+            oauth2::AccessToken::new("mock-access-token".to_string()),
+            oauth2::basic::BasicTokenType::Bearer,
+            oauth2::EmptyExtraTokenFields {},
+        );
+        expected_token.set_refresh_token(Some(oauth2::RefreshToken::new(
+            "mock-refresh-token".to_string(),
+        )));
+        expected_token.set_expires_in(Some(&Duration::from_secs(3600)));
+        let expected_html = login_response("test", "success", &expected_token).0;
+
+        assert_eq!(String::from_utf8_lossy(&body), expected_html);
+    }
+
+    fn token_with(refresh_token: bool, expires_in: bool) -> BasicTokenResponse {
+        let mut token = BasicTokenResponse::new(
+            oauth2::AccessToken::new("access-token".to_string()),
+            oauth2::basic::BasicTokenType::Bearer,
+            oauth2::EmptyExtraTokenFields {},
+        );
+
+        if refresh_token {
+            token.set_refresh_token(Some(oauth2::RefreshToken::new(
+                "refresh-token".to_string(),
+            )));
+        }
+
+        if expires_in {
+            token.set_expires_in(Some(&Duration::from_secs(3600)));
+        }
+
+        token
+    }
+
+    #[test]
+    fn login_response_includes_refresh_token_and_expiry_when_present() {
+        let html = login_response("github", "success", &token_with(true, true)).0;
+
+        assert!(html.contains(r#""refresh_token":"refresh-token""#));
+        assert!(html.contains(r#""expires_in":3600"#));
+    }
+
+    #[test]
+    fn login_response_omits_refresh_token_and_expiry_when_absent() {
+        let html = login_response("github", "success", &token_with(false, false)).0;
+
+        assert!(!html.contains("refresh_token"));
+        assert!(!html.contains("expires_in"));
+    }
 }
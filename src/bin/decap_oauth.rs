@@ -1,5 +1,5 @@
 use axum::Router;
-use decap_oauth::oauth_router;
+use decap_oauth::oauth_router_from_env;
 use std::env;
 use std::process::exit;
 use tokio::net::TcpListener;
@@ -49,8 +49,9 @@ async fn main() {
     check_var("OAUTH_CLIENT_ID");
     check_var("OAUTH_SECRET");
     check_var("OAUTH_ORIGINS");
+    check_var("OAUTH_REDIRECT_URI");
 
-    let app = Router::new().merge(oauth_router());
+    let app = Router::new().merge(oauth_router_from_env());
 
     let args = parse_args();
 